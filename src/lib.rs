@@ -4,15 +4,16 @@ use {
     leaf::LeafIndex,
     risc0_zkvm::sha::{Digest, Sha256},
     std::{
-        borrow::Cow,
-        collections::{btree_map::Entry, BTreeMap},
+        collections::{BTreeMap, BTreeSet},
         marker::PhantomData,
         ops::Deref,
     },
+    store::{InMemoryStore, NodeStore},
 };
 
 mod empty_roots;
 pub mod leaf;
+pub mod store;
 #[cfg(test)]
 mod tests;
 
@@ -20,40 +21,51 @@ mod tests;
 /// The keys and values are both 256-bit.
 /// The data structure is based on a sparse merkle tree where
 /// all leaves exist at depth 64.
+///
+/// Generic over its backing storage `S`; defaults to the in-memory [`InMemoryStore`].
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Smt<H: Sha256> {
+pub struct Smt<H: Sha256, S: NodeStore<H> = InMemoryStore<H>> {
     hasher: PhantomData<H>,
     root: H::DigestPtr,
-    leaves: BTreeMap<u64, SmtLeaf<H>>,
-    inner_nodes: BTreeMap<NodeIndex, InnerNode>,
+    store: S,
+    /// Leaves changed by [`Smt::insert_deferred`]/[`Smt::remove_deferred`] whose path
+    /// to the root hasn't been recomputed yet. Cleared by [`Smt::flush`].
+    dirty: BTreeSet<LeafIndex>,
+    /// Bumped by every mutation, so a [`MutationSet`] can tell whether `self` has
+    /// changed since it was computed.
+    generation: u64,
 }
 
-impl<H: Sha256> Default for Smt<H> {
+impl<H: Sha256, S: NodeStore<H> + Default> Default for Smt<H, S> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<H: Sha256> Smt<H> {
+impl<H: Sha256, S: NodeStore<H> + Default> Smt<H, S> {
     pub fn new() -> Self {
         let empty_subtree = EmptySubtreeRoots::entry(LEAF_DEPTH, 1);
         let root = H::hash_pair(empty_subtree, empty_subtree);
         Self {
             hasher: PhantomData,
             root,
-            leaves: BTreeMap::new(),
-            inner_nodes: BTreeMap::new(),
+            store: S::default(),
+            dirty: BTreeSet::new(),
+            generation: 0,
         }
     }
+}
 
+impl<H: Sha256, S: NodeStore<H>> Smt<H, S> {
     /// Get the value associated with the key, along with a proof this lookup is correct.
     /// Note: by default all keys are associated with `Value::EMPTY`.
-    pub fn get(&self, key: &Key) -> (Value, SmtProof<H>) {
+    pub fn get(&mut self, key: &Key) -> (Value, SmtProof<H>) {
+        self.flush();
+
         let leaf_index = key_to_leaf_index(key);
         let leaf = self
-            .leaves
-            .get(&leaf_index.value)
-            .cloned()
+            .store
+            .get_leaf(leaf_index.value)
             .unwrap_or_else(|| SmtLeaf::new(leaf_index));
         let value = leaf.get_direct(key).copied().unwrap_or(Value::EMPTY);
 
@@ -63,11 +75,10 @@ impl<H: Sha256> Smt<H> {
             .map(|_| {
                 let side = index.get_side();
                 index.move_up();
-                let inner_node = self.get_inner_node(&index);
-                let InnerNode { left, right } = inner_node.as_ref();
+                let InnerNode { left, right } = self.get_inner_node(&index);
                 match side {
-                    Side::Left => *right,
-                    Side::Right => *left,
+                    Side::Left => right,
+                    Side::Right => left,
                 }
             })
             .collect();
@@ -79,6 +90,58 @@ impl<H: Sha256> Smt<H> {
         (value, proof)
     }
 
+    /// Get the values associated with several keys at once, along with a single proof
+    /// that covers all of them. Shared path nodes between the queried keys are stored
+    /// only once in the returned proof, so this is cheaper to verify than checking each
+    /// key's own `SmtProof` independently.
+    pub fn get_many(&mut self, keys: &[Key]) -> (Vec<Value>, SmtMultiProof<H>) {
+        self.flush();
+
+        let mut leaves_by_index: BTreeMap<u64, SmtLeaf<H>> = BTreeMap::new();
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            let leaf_index = key_to_leaf_index(key);
+            let leaf = leaves_by_index.entry(leaf_index.value).or_insert_with(|| {
+                self.store
+                    .get_leaf(leaf_index.value)
+                    .unwrap_or_else(|| SmtLeaf::new(leaf_index))
+            });
+            values.push(leaf.get_direct(key).copied().unwrap_or(Value::EMPTY));
+        }
+
+        // Walk all the involved paths to the root together, level by level, recording
+        // each sibling digest at most once and skipping siblings that are themselves
+        // one of the queried leaves' ancestors (the verifier recomputes those from the
+        // leaves it already has).
+        let mut siblings: BTreeMap<NodeIndex, Digest> = BTreeMap::new();
+        let mut active: BTreeSet<NodeIndex> = leaves_by_index
+            .keys()
+            .map(|&value| NodeIndex {
+                depth: LEAF_DEPTH,
+                value,
+            })
+            .collect();
+
+        while active.iter().next().is_some_and(|index| index.depth > 0) {
+            let mut parents = BTreeSet::new();
+            for &index in &active {
+                let mut sibling_index = index;
+                sibling_index.value ^= 1;
+                if !active.contains(&sibling_index) {
+                    siblings.insert(sibling_index, self.sibling_digest(&index));
+                }
+
+                let mut parent = index;
+                parent.move_up();
+                parents.insert(parent);
+            }
+            active = parents;
+        }
+
+        let leaves = leaves_by_index.into_values().collect();
+        (values, SmtMultiProof { leaves, siblings })
+    }
+
     /// Insert a key-value pair into the SMT, returning the old value
     /// associated with that key.
     /// Note: by default all keys are associated with `Value::EMPTY`.
@@ -86,21 +149,24 @@ impl<H: Sha256> Smt<H> {
         if value == Value::EMPTY {
             return self.remove(&key);
         }
+        self.flush();
 
         let leaf_index = key_to_leaf_index(&key);
 
-        let leaf = self
-            .leaves
-            .entry(leaf_index.value)
-            .or_insert_with(|| SmtLeaf::new(leaf_index));
+        let mut leaf = self
+            .store
+            .get_leaf(leaf_index.value)
+            .unwrap_or_else(|| SmtLeaf::new(leaf_index));
         let old_value = leaf.insert(key, value);
+        let leaf_hash = leaf.hash();
+        self.store.insert_leaf(leaf_index.value, leaf);
 
         if old_value == value {
             return value;
         }
 
-        let leaf_hash = leaf.hash();
         self.recompute_nodes_from_leaf_to_root(leaf_index, leaf_hash);
+        self.generation += 1;
 
         old_value
     }
@@ -108,29 +174,207 @@ impl<H: Sha256> Smt<H> {
     /// Remove a key from the SMT.
     /// Note: even after this operation the key is associated with `Value::EMPTY`.
     pub fn remove(&mut self, key: &Key) -> Value {
+        self.flush();
+
         let leaf_index = key_to_leaf_index(key);
 
-        let (old_value, leaf_hash) = match self.leaves.entry(leaf_index.value) {
-            Entry::Vacant(_) => return Value::EMPTY,
-            Entry::Occupied(mut leaf) => {
-                let old_value = match leaf.get_mut().remove(key) {
-                    None => return Value::EMPTY,
-                    Some(old_value) => old_value,
-                };
-                (old_value, leaf.get().hash())
-            }
+        let mut leaf = match self.store.get_leaf(leaf_index.value) {
+            None => return Value::EMPTY,
+            Some(leaf) => leaf,
+        };
+        let old_value = match leaf.remove(key) {
+            None => return Value::EMPTY,
+            Some(old_value) => old_value,
         };
+        let leaf_hash = leaf.hash();
+        if leaf.is_empty() {
+            self.store.remove_leaf(leaf_index.value);
+        } else {
+            self.store.insert_leaf(leaf_index.value, leaf);
+        }
 
         self.recompute_nodes_from_leaf_to_root(leaf_index, leaf_hash);
+        self.generation += 1;
+
+        old_value
+    }
+
+    /// Insert a key-value pair without recomputing the path to the root; see
+    /// [`Smt::flush`].
+    /// Note: by default all keys are associated with `Value::EMPTY`.
+    pub fn insert_deferred(&mut self, key: Key, value: Value) -> Value {
+        if value == Value::EMPTY {
+            return self.remove_deferred(&key);
+        }
+
+        let leaf_index = key_to_leaf_index(&key);
+
+        let mut leaf = self
+            .store
+            .get_leaf(leaf_index.value)
+            .unwrap_or_else(|| SmtLeaf::new(leaf_index));
+        let old_value = leaf.insert(key, value);
+        self.store.insert_leaf(leaf_index.value, leaf);
+        self.dirty.insert(leaf_index);
+        self.generation += 1;
+
+        old_value
+    }
+
+    /// Remove a key without recomputing the path to the root; see
+    /// [`Smt::insert_deferred`].
+    /// Note: even after this operation the key is associated with `Value::EMPTY`.
+    pub fn remove_deferred(&mut self, key: &Key) -> Value {
+        let leaf_index = key_to_leaf_index(key);
+
+        let mut leaf = match self.store.get_leaf(leaf_index.value) {
+            None => return Value::EMPTY,
+            Some(leaf) => leaf,
+        };
+        let old_value = match leaf.remove(key) {
+            None => return Value::EMPTY,
+            Some(old_value) => old_value,
+        };
+        if leaf.is_empty() {
+            self.store.remove_leaf(leaf_index.value);
+        } else {
+            self.store.insert_leaf(leaf_index.value, leaf);
+        }
+        self.dirty.insert(leaf_index);
+        self.generation += 1;
 
         old_value
     }
 
+    /// Recompute and commit the paths to the root for every leaf left dirty by
+    /// [`Smt::insert_deferred`]/[`Smt::remove_deferred`]. A no-op if nothing is dirty.
+    pub fn flush(&mut self) {
+        if self.dirty.is_empty() {
+            return;
+        }
+        let dirty = std::mem::take(&mut self.dirty);
+
+        let leaf_digests: BTreeMap<u64, Digest> = dirty
+            .into_iter()
+            .map(|leaf_index| {
+                let hash = self
+                    .store
+                    .get_leaf(leaf_index.value)
+                    .and_then(|leaf| leaf.hash())
+                    .as_deref()
+                    .copied()
+                    .unwrap_or(Digest::ZERO);
+                (leaf_index.value, hash)
+            })
+            .collect();
+
+        let (root, inner_node_updates) = self.compute_inner_node_updates(&leaf_digests);
+        for (index, inner_node) in inner_node_updates {
+            match inner_node {
+                Some(inner_node) => {
+                    self.store.insert_inner_node(index, inner_node);
+                }
+                None => {
+                    self.store.remove_inner_node(index);
+                }
+            }
+        }
+        if let Some(root) = root {
+            self.root = root;
+        }
+        self.generation += 1;
+    }
+
     /// Get the root of the SMT.
-    pub fn get_root(&self) -> &Digest {
+    pub fn get_root(&mut self) -> &Digest {
+        self.flush();
         &self.root
     }
 
+    /// Work out the [`MutationSet`] for applying several key-value changes at once,
+    /// without applying `changes` to `self`. Shared ancestors are only hashed once.
+    pub fn compute_mutations(
+        &mut self,
+        changes: impl IntoIterator<Item = (Key, Value)>,
+    ) -> MutationSet<H> {
+        self.flush();
+
+        let mut leaves_by_index: BTreeMap<u64, SmtLeaf<H>> = BTreeMap::new();
+        for (key, value) in changes {
+            let leaf_index = key_to_leaf_index(&key);
+            let leaf = leaves_by_index.entry(leaf_index.value).or_insert_with(|| {
+                self.store
+                    .get_leaf(leaf_index.value)
+                    .unwrap_or_else(|| SmtLeaf::new(leaf_index))
+            });
+            if value == Value::EMPTY {
+                leaf.remove(&key);
+            } else {
+                leaf.insert(key, value);
+            }
+        }
+
+        let leaf_digests: BTreeMap<u64, Digest> = leaves_by_index
+            .iter()
+            .map(|(&index, leaf)| {
+                let hash = leaf.hash().as_deref().copied().unwrap_or(Digest::ZERO);
+                (index, hash)
+            })
+            .collect();
+        let leaf_updates: BTreeMap<u64, Option<SmtLeaf<H>>> = leaves_by_index
+            .into_iter()
+            .map(|(index, leaf)| (index, (!leaf.is_empty()).then_some(leaf)))
+            .collect();
+
+        let (root, inner_node_updates) = self.compute_inner_node_updates(&leaf_digests);
+        let root = root.unwrap_or_else(|| self.root.clone());
+
+        MutationSet {
+            root,
+            leaf_updates,
+            inner_node_updates,
+            generation: self.generation,
+        }
+    }
+
+    /// Commit a [`MutationSet`] previously computed by [`Smt::compute_mutations`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` has been mutated since the `MutationSet` was computed, since
+    /// applying it would silently discard that intervening change.
+    pub fn apply_mutations(&mut self, mutations: MutationSet<H>) {
+        assert_eq!(
+            self.generation, mutations.generation,
+            "MutationSet is stale: `self` was mutated after `compute_mutations` produced it"
+        );
+
+        for (index, leaf) in mutations.leaf_updates {
+            match leaf {
+                Some(leaf) => {
+                    self.store.insert_leaf(index, leaf);
+                }
+                None => {
+                    self.store.remove_leaf(index);
+                }
+            }
+        }
+
+        for (index, inner_node) in mutations.inner_node_updates {
+            match inner_node {
+                Some(inner_node) => {
+                    self.store.insert_inner_node(index, inner_node);
+                }
+                None => {
+                    self.store.remove_inner_node(index);
+                }
+            }
+        }
+
+        self.root = mutations.root;
+        self.generation += 1;
+    }
+
     fn recompute_nodes_from_leaf_to_root(
         &mut self,
         leaf_index: LeafIndex,
@@ -143,11 +387,10 @@ impl<H: Sha256> Smt<H> {
                 let mut index: NodeIndex = leaf_index.into();
                 let side = index.get_side();
                 index.move_up();
-                let inner_node = self.get_inner_node(&index);
-                let InnerNode { left, right } = inner_node.as_ref();
+                let InnerNode { left, right } = self.get_inner_node(&index);
                 let (left, right) = match side {
-                    Side::Left => (node_hash, right),
-                    Side::Right => (left, node_hash),
+                    Side::Left => (node_hash, &right),
+                    Side::Right => (&left, node_hash),
                 };
                 (H::hash_pair(left, right), index)
             }
@@ -155,11 +398,10 @@ impl<H: Sha256> Smt<H> {
         for node_depth in (0..index.depth).rev() {
             let side = index.get_side();
             index.move_up();
-            let inner_node = self.get_inner_node(&index);
-            let InnerNode { left, right } = inner_node.as_ref();
+            let InnerNode { left, right } = self.get_inner_node(&index);
             let (left, right) = match side {
-                Side::Left => (node_hash.deref(), right),
-                Side::Right => (left, node_hash.deref()),
+                Side::Left => (node_hash.deref(), &right),
+                Side::Right => (&left, node_hash.deref()),
             };
             let new_inner_node = InnerNode {
                 left: *left,
@@ -168,25 +410,120 @@ impl<H: Sha256> Smt<H> {
             node_hash = H::hash_pair(left, right);
 
             if node_hash.deref() == EmptySubtreeRoots::entry(LEAF_DEPTH, node_depth) {
-                self.inner_nodes.remove(&index);
+                self.store.remove_inner_node(index);
             } else {
-                self.inner_nodes.insert(index, new_inner_node);
+                self.store.insert_inner_node(index, new_inner_node);
             }
         }
         self.root = node_hash;
     }
 
-    fn get_inner_node<'a>(&'a self, index: &NodeIndex) -> Cow<'a, InnerNode> {
-        self.inner_nodes
-            .get(index)
-            .map(Cow::Borrowed)
-            .unwrap_or_else(|| {
-                let node = EmptySubtreeRoots::entry(LEAF_DEPTH, index.depth + 1);
-                Cow::Owned(InnerNode {
-                    left: *node,
-                    right: *node,
-                })
-            })
+    /// Get the digest of the sibling of `index`, i.e. the node that would need to be
+    /// hashed together with `index` to obtain their shared parent's digest.
+    fn sibling_digest(&self, index: &NodeIndex) -> Digest {
+        let side = index.get_side();
+        let mut parent = *index;
+        parent.move_up();
+        let inner_node = self.get_inner_node(&parent);
+        match side {
+            Side::Left => inner_node.right,
+            Side::Right => inner_node.left,
+        }
+    }
+
+    fn get_inner_node(&self, index: &NodeIndex) -> InnerNode {
+        self.store.get_inner_node(*index).unwrap_or_else(|| {
+            let node = EmptySubtreeRoots::entry(LEAF_DEPTH, index.depth + 1);
+            InnerNode {
+                left: *node,
+                right: *node,
+            }
+        })
+    }
+
+    /// Recompute the inner nodes above the given leaf digests, coalescing shared
+    /// ancestors so each one is only hashed once, without touching `self.store`.
+    /// Returns the new root (`None` if `leaf_digests` is empty) and the updated inner
+    /// nodes keyed by their `NodeIndex`, with `None` meaning the node is now an empty
+    /// subtree.
+    fn compute_inner_node_updates(
+        &self,
+        leaf_digests: &BTreeMap<u64, Digest>,
+    ) -> (Option<H::DigestPtr>, BTreeMap<NodeIndex, Option<InnerNode>>) {
+        let mut inner_node_updates: BTreeMap<NodeIndex, Option<InnerNode>> = BTreeMap::new();
+
+        // Combine each touched leaf with its sibling (another touched leaf, if one
+        // shares the same parent, or the existing tree otherwise) to seed the frontier
+        // of modified inner nodes one level up from the leaves.
+        let mut frontier: BTreeMap<NodeIndex, H::DigestPtr> = BTreeMap::new();
+        let mut handled_parents = BTreeSet::new();
+        for (&leaf_index, &hash) in leaf_digests {
+            let child_index = NodeIndex {
+                depth: LEAF_DEPTH,
+                value: leaf_index,
+            };
+            let mut parent_index = child_index;
+            parent_index.move_up();
+            if !handled_parents.insert(parent_index) {
+                continue;
+            }
+
+            let mut sibling_index = child_index;
+            sibling_index.value ^= 1;
+            let sibling_hash = leaf_digests
+                .get(&sibling_index.value)
+                .copied()
+                .unwrap_or_else(|| self.sibling_digest(&child_index));
+
+            let (left, right) = match child_index.get_side() {
+                Side::Left => (hash, sibling_hash),
+                Side::Right => (sibling_hash, hash),
+            };
+            let new_hash = H::hash_pair(&left, &right);
+            if new_hash.deref() == EmptySubtreeRoots::entry(LEAF_DEPTH, parent_index.depth) {
+                inner_node_updates.insert(parent_index, None);
+            } else {
+                inner_node_updates.insert(parent_index, Some(InnerNode { left, right }));
+            }
+            frontier.insert(parent_index, new_hash);
+        }
+
+        // Walk the rest of the way to the root, coalescing shared ancestors the same
+        // way.
+        while frontier.iter().next().is_some_and(|(index, _)| index.depth > 0) {
+            let mut next = BTreeMap::new();
+            let mut handled = BTreeSet::new();
+            for (&index, hash) in &frontier {
+                let mut parent_index = index;
+                parent_index.move_up();
+                if !handled.insert(parent_index) {
+                    continue;
+                }
+
+                let mut sibling_index = index;
+                sibling_index.value ^= 1;
+                let sibling_hash = match frontier.get(&sibling_index) {
+                    Some(sibling_hash) => *sibling_hash.deref(),
+                    None => self.sibling_digest(&index),
+                };
+
+                let (left, right) = match index.get_side() {
+                    Side::Left => (*hash.deref(), sibling_hash),
+                    Side::Right => (sibling_hash, *hash.deref()),
+                };
+                let new_hash = H::hash_pair(&left, &right);
+                if new_hash.deref() == EmptySubtreeRoots::entry(LEAF_DEPTH, parent_index.depth) {
+                    inner_node_updates.insert(parent_index, None);
+                } else {
+                    inner_node_updates.insert(parent_index, Some(InnerNode { left, right }));
+                }
+                next.insert(parent_index, new_hash);
+            }
+            frontier = next;
+        }
+
+        let root = frontier.into_values().next();
+        (root, inner_node_updates)
     }
 }
 
@@ -204,12 +541,18 @@ impl Value {
     }
 }
 
-pub struct SmtProof<H> {
-    pub path: MerklePath,
+/// A Merkle path, as used by a [`SmtProof`] to recompute a root: either the full
+/// [`MerklePath`] or the space-saving [`CompactMerklePath`].
+pub trait ProofPath {
+    fn compute_root<H: Sha256>(&self, index: u64, init_hash: &Digest) -> H::DigestPtr;
+}
+
+pub struct SmtProof<H, P = MerklePath> {
+    pub path: P,
     pub leaf: SmtLeaf<H>,
 }
 
-impl<H: Sha256> SmtProof<H> {
+impl<H: Sha256, P: ProofPath> SmtProof<H, P> {
     pub fn verify(&self, key: &Key, value: &Value, root: &Digest) -> bool {
         let leaf_value = match self.leaf.get(key) {
             Some(v) => v,
@@ -234,6 +577,117 @@ impl<H: Sha256> SmtProof<H> {
     }
 }
 
+impl<H> SmtProof<H, MerklePath> {
+    /// Shrink this proof's path down to a [`CompactMerklePath`], dropping siblings that
+    /// equal the canonical empty-subtree root for their level.
+    pub fn compact(&self) -> SmtProof<H, CompactMerklePath> {
+        SmtProof {
+            path: self.path.compact(),
+            leaf: self.leaf.clone(),
+        }
+    }
+}
+
+impl<H> SmtProof<H, CompactMerklePath> {
+    /// Expand this proof's path back into a full 64-sibling [`MerklePath`].
+    pub fn from_compact(self) -> SmtProof<H, MerklePath> {
+        SmtProof {
+            path: MerklePath::from_compact(&self.path),
+            leaf: self.leaf,
+        }
+    }
+}
+
+/// A proof of inclusion (or non-inclusion) for several keys at once, against a single
+/// root. Sibling digests shared between the queried keys' paths are stored only once,
+/// keyed by the `NodeIndex` they belong to, instead of repeating a full `MerklePath`
+/// per key.
+pub struct SmtMultiProof<H> {
+    pub leaves: Vec<SmtLeaf<H>>,
+    siblings: BTreeMap<NodeIndex, Digest>,
+}
+
+impl<H: Sha256> SmtMultiProof<H> {
+    /// Check that every `(key, value)` pair is consistent with `root` according to
+    /// this proof.
+    pub fn verify_many(&self, entries: &[(Key, Value)], root: &Digest) -> bool {
+        let mut leaves_by_index: BTreeMap<u64, &SmtLeaf<H>> = BTreeMap::new();
+        for leaf in &self.leaves {
+            leaves_by_index.insert(leaf.index().value, leaf);
+        }
+
+        for (key, value) in entries {
+            let leaf_index = key_to_leaf_index(key);
+            match leaves_by_index.get(&leaf_index.value) {
+                Some(leaf) if leaf.get(key) == Some(value) => {}
+                _ => return false,
+            }
+        }
+
+        match self.compute_root() {
+            Some(computed_root) => &computed_root == root,
+            None => false,
+        }
+    }
+
+    /// Recompute the root implied by this proof's leaves and sibling digests.
+    fn compute_root(&self) -> Option<Digest> {
+        let mut current: BTreeMap<NodeIndex, Digest> = self
+            .leaves
+            .iter()
+            .map(|leaf| {
+                let index = NodeIndex {
+                    depth: LEAF_DEPTH,
+                    value: leaf.index().value,
+                };
+                let hash = leaf.hash().as_deref().copied().unwrap_or(Digest::ZERO);
+                (index, hash)
+            })
+            .collect();
+
+        while current.iter().next()?.0.depth > 0 {
+            let mut next = BTreeMap::new();
+            for (&index, &hash) in &current {
+                let mut sibling_index = index;
+                sibling_index.value ^= 1;
+                let sibling_hash = match current.get(&sibling_index) {
+                    Some(hash) => *hash,
+                    None => *self.siblings.get(&sibling_index)?,
+                };
+
+                let mut parent = index;
+                parent.move_up();
+                let parent_hash = match index.get_side() {
+                    Side::Left => H::hash_pair(&hash, &sibling_hash),
+                    Side::Right => H::hash_pair(&sibling_hash, &hash),
+                };
+                next.insert(parent, *parent_hash);
+            }
+            current = next;
+        }
+
+        current.into_values().next()
+    }
+}
+
+/// A precomputed but not-yet-applied batch of changes to an [`Smt`], produced by
+/// [`Smt::compute_mutations`] and committed with [`Smt::apply_mutations`].
+pub struct MutationSet<H: Sha256> {
+    root: H::DigestPtr,
+    leaf_updates: BTreeMap<u64, Option<SmtLeaf<H>>>,
+    inner_node_updates: BTreeMap<NodeIndex, Option<InnerNode>>,
+    /// The [`Smt::generation`] this was computed against; checked by
+    /// [`Smt::apply_mutations`] to reject a mutation set that's gone stale.
+    generation: u64,
+}
+
+impl<H: Sha256> MutationSet<H> {
+    /// The root the tree would have after this mutation set is applied.
+    pub fn root(&self) -> &Digest {
+        &self.root
+    }
+}
+
 pub struct MerklePath {
     pub nodes: Vec<Digest>,
 }
@@ -243,7 +697,42 @@ impl MerklePath {
         Self { nodes }
     }
 
-    pub fn compute_root<H: Sha256>(&self, index: u64, init_hash: &Digest) -> H::DigestPtr {
+    /// Shrink this path down to a [`CompactMerklePath`], dropping siblings that equal
+    /// the canonical empty-subtree root for their level.
+    pub fn compact(&self) -> CompactMerklePath {
+        let mut present = 0u64;
+        let mut nodes = Vec::new();
+        for (i, digest) in self.nodes.iter().enumerate() {
+            let sibling_depth = LEAF_DEPTH - i as u8;
+            if digest != EmptySubtreeRoots::entry(LEAF_DEPTH, sibling_depth) {
+                present |= 1 << i;
+                nodes.push(*digest);
+            }
+        }
+        CompactMerklePath { present, nodes }
+    }
+
+    /// Expand a [`CompactMerklePath`] back into a full `LEAF_DEPTH`-sibling path.
+    pub fn from_compact(compact: &CompactMerklePath) -> Self {
+        let mut nodes = Vec::with_capacity(LEAF_DEPTH as usize);
+        let mut stored = compact.nodes.iter();
+        for i in 0..LEAF_DEPTH as usize {
+            let sibling_depth = LEAF_DEPTH - i as u8;
+            let digest = if compact.present & (1 << i) != 0 {
+                *stored
+                    .next()
+                    .expect("CompactMerklePath is missing a stored sibling")
+            } else {
+                *EmptySubtreeRoots::entry(LEAF_DEPTH, sibling_depth)
+            };
+            nodes.push(digest);
+        }
+        Self { nodes }
+    }
+}
+
+impl ProofPath for MerklePath {
+    fn compute_root<H: Sha256>(&self, index: u64, init_hash: &Digest) -> H::DigestPtr {
         let mut index = NodeIndex {
             depth: self.nodes.len() as u8,
             value: index,
@@ -267,10 +756,70 @@ impl MerklePath {
     }
 }
 
+/// A [`MerklePath`] that omits siblings equal to the canonical empty-subtree root for
+/// their level, keeping only a bitmap of which levels have a "real" sibling plus those
+/// siblings' digests in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactMerklePath {
+    present: u64,
+    nodes: Vec<Digest>,
+}
+
+impl CompactMerklePath {
+    fn sibling_at<'a>(
+        &'a self,
+        level: u8,
+        parent_depth: u8,
+        stored: &mut impl Iterator<Item = &'a Digest>,
+    ) -> &'a Digest {
+        if self.present & (1 << level) != 0 {
+            stored
+                .next()
+                .expect("CompactMerklePath is missing a stored sibling")
+        } else {
+            EmptySubtreeRoots::entry(LEAF_DEPTH, parent_depth + 1)
+        }
+    }
+}
+
+impl ProofPath for CompactMerklePath {
+    fn compute_root<H: Sha256>(&self, index: u64, init_hash: &Digest) -> H::DigestPtr {
+        let mut index = NodeIndex {
+            depth: LEAF_DEPTH,
+            value: index,
+        };
+        let mut stored = self.nodes.iter();
+
+        let side = index.get_side();
+        index.move_up();
+        let sibling = self.sibling_at(0, index.depth, &mut stored);
+        let mut node_hash = match side {
+            Side::Left => H::hash_pair(init_hash, sibling),
+            Side::Right => H::hash_pair(sibling, init_hash),
+        };
+
+        for level in 1..LEAF_DEPTH {
+            let side = index.get_side();
+            index.move_up();
+            let sibling = self.sibling_at(level, index.depth, &mut stored);
+            node_hash = match side {
+                Side::Left => H::hash_pair(node_hash.deref(), sibling),
+                Side::Right => H::hash_pair(sibling, node_hash.deref()),
+            };
+        }
+
+        node_hash
+    }
+}
+
+/// The position of a node (leaf or inner) within the tree: how many levels below the
+/// root it sits (`depth`) and its index among the nodes at that depth (`value`). A
+/// [`NodeStore`] treats this as an opaque key for its inner-node map.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct NodeIndex {
-    depth: u8,
-    value: u64,
+#[derive(Hash)]
+pub struct NodeIndex {
+    pub depth: u8,
+    pub value: u64,
 }
 
 impl NodeIndex {
@@ -293,8 +842,10 @@ enum Side {
     Right,
 }
 
+/// The two children of a node in the tree, keyed by their shared parent's [`NodeIndex`]
+/// in a [`NodeStore`].
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct InnerNode {
-    left: Digest,
-    right: Digest,
+pub struct InnerNode {
+    pub left: Digest,
+    pub right: Digest,
 }
@@ -0,0 +1,57 @@
+use {
+    crate::{leaf::SmtLeaf, InnerNode, NodeIndex},
+    std::collections::BTreeMap,
+};
+
+/// Backing storage for an [`Smt`](crate::Smt)'s leaves and inner nodes.
+pub trait NodeStore<H> {
+    fn get_leaf(&self, index: u64) -> Option<SmtLeaf<H>>;
+    fn insert_leaf(&mut self, index: u64, leaf: SmtLeaf<H>) -> Option<SmtLeaf<H>>;
+    fn remove_leaf(&mut self, index: u64) -> Option<SmtLeaf<H>>;
+
+    fn get_inner_node(&self, index: NodeIndex) -> Option<InnerNode>;
+    fn insert_inner_node(&mut self, index: NodeIndex, node: InnerNode) -> Option<InnerNode>;
+    fn remove_inner_node(&mut self, index: NodeIndex) -> Option<InnerNode>;
+}
+
+/// The default [`NodeStore`]: keeps every leaf and inner node in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InMemoryStore<H> {
+    leaves: BTreeMap<u64, SmtLeaf<H>>,
+    inner_nodes: BTreeMap<NodeIndex, InnerNode>,
+}
+
+impl<H> Default for InMemoryStore<H> {
+    fn default() -> Self {
+        Self {
+            leaves: BTreeMap::new(),
+            inner_nodes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<H> NodeStore<H> for InMemoryStore<H> {
+    fn get_leaf(&self, index: u64) -> Option<SmtLeaf<H>> {
+        self.leaves.get(&index).cloned()
+    }
+
+    fn insert_leaf(&mut self, index: u64, leaf: SmtLeaf<H>) -> Option<SmtLeaf<H>> {
+        self.leaves.insert(index, leaf)
+    }
+
+    fn remove_leaf(&mut self, index: u64) -> Option<SmtLeaf<H>> {
+        self.leaves.remove(&index)
+    }
+
+    fn get_inner_node(&self, index: NodeIndex) -> Option<InnerNode> {
+        self.inner_nodes.get(&index).cloned()
+    }
+
+    fn insert_inner_node(&mut self, index: NodeIndex, node: InnerNode) -> Option<InnerNode> {
+        self.inner_nodes.insert(index, node)
+    }
+
+    fn remove_inner_node(&mut self, index: NodeIndex) -> Option<InnerNode> {
+        self.inner_nodes.remove(&index)
+    }
+}
@@ -1,8 +1,75 @@
-use {super::*, risc0_zkvm::sha::Impl};
+use {super::*, risc0_zkvm::sha::Impl, std::collections::HashMap};
+
+/// A second `NodeStore` impl, distinct from `InMemoryStore`, so tests can exercise
+/// `Smt`'s storage abstraction rather than only ever its default backend.
+struct HashMapStore<H> {
+    leaves: HashMap<u64, SmtLeaf<H>>,
+    inner_nodes: HashMap<NodeIndex, InnerNode>,
+}
+
+impl<H> Default for HashMapStore<H> {
+    fn default() -> Self {
+        Self {
+            leaves: HashMap::new(),
+            inner_nodes: HashMap::new(),
+        }
+    }
+}
+
+impl<H> NodeStore<H> for HashMapStore<H> {
+    fn get_leaf(&self, index: u64) -> Option<SmtLeaf<H>> {
+        self.leaves.get(&index).cloned()
+    }
+
+    fn insert_leaf(&mut self, index: u64, leaf: SmtLeaf<H>) -> Option<SmtLeaf<H>> {
+        self.leaves.insert(index, leaf)
+    }
+
+    fn remove_leaf(&mut self, index: u64) -> Option<SmtLeaf<H>> {
+        self.leaves.remove(&index)
+    }
+
+    fn get_inner_node(&self, index: NodeIndex) -> Option<InnerNode> {
+        self.inner_nodes.get(&index).cloned()
+    }
+
+    fn insert_inner_node(&mut self, index: NodeIndex, node: InnerNode) -> Option<InnerNode> {
+        self.inner_nodes.insert(index, node)
+    }
+
+    fn remove_inner_node(&mut self, index: NodeIndex) -> Option<InnerNode> {
+        self.inner_nodes.remove(&index)
+    }
+}
+
+#[test]
+fn test_custom_node_store() {
+    let mut smt = Smt::<Impl, HashMapStore<Impl>>::new();
+    let mut reference = Smt::<Impl>::new();
+
+    let keys_and_values: Vec<(Key, Value)> = KeyIter::default()
+        .map(|key| (key, Value(key.0)))
+        .collect();
+    for (key, value) in keys_and_values.iter().copied() {
+        assert_eq!(smt.insert(key, value), Value::EMPTY);
+        reference.insert(key, value);
+    }
+    assert_eq!(smt.get_root(), reference.get_root());
+
+    for (key, value) in keys_and_values.iter().copied() {
+        let (smt_value, proof) = smt.get(&key);
+        assert_eq!(smt_value, value);
+        assert!(proof.verify(&key, &value, smt.get_root()));
+    }
+
+    let (key, value) = keys_and_values[1];
+    assert_eq!(smt.remove(&key), value);
+    assert_ne!(smt.get_root(), reference.get_root());
+}
 
 #[test]
 fn test_create_empty_tree() {
-    let smt = Smt::<Impl>::new();
+    let mut smt = Smt::<Impl>::new();
     assert_eq!(smt.get_root(), EmptySubtreeRoots::entry(LEAF_DEPTH, 0));
 
     let key = Key([0; 8]);
@@ -43,6 +110,103 @@ fn test_insert() {
     }
 }
 
+#[test]
+fn test_get_many() {
+    let mut smt = Smt::<Impl>::new();
+
+    let keys_and_values: Vec<(Key, Value)> = KeyIter::default()
+        .map(|key| (key, Value(key.0)))
+        .collect();
+    for (key, value) in keys_and_values.iter().copied() {
+        smt.insert(key, value);
+    }
+
+    let keys: Vec<Key> = keys_and_values.iter().map(|(key, _)| *key).collect();
+    let (values, proof) = smt.get_many(&keys);
+    assert_eq!(values, keys_and_values.iter().map(|(_, v)| *v).collect::<Vec<_>>());
+    assert!(proof.verify_many(&keys_and_values, smt.get_root()));
+
+    // A proof is only valid for the root it was generated against.
+    smt.insert(Key([9; 8]), Value([9; 8]));
+    assert!(!proof.verify_many(&keys_and_values, smt.get_root()));
+}
+
+#[test]
+fn test_compact_proof() {
+    let mut smt = Smt::<Impl>::new();
+
+    let key = Key([1, 0, 0, 0, 0, 0, 0, 0]);
+    let value = Value(Impl::hash_words(&[]).as_words().try_into().unwrap());
+    smt.insert(key, value);
+
+    let (looked_up_value, proof) = smt.get(&key);
+    assert_eq!(looked_up_value, value);
+
+    // Almost every sibling is the empty-subtree root, so the compact form should be
+    // far smaller than the full path.
+    let compact_proof = proof.compact();
+    assert!(compact_proof.path.nodes.len() < proof.path.nodes.len());
+    assert!(compact_proof.verify(&key, &value, smt.get_root()));
+
+    let round_tripped = compact_proof.from_compact();
+    assert_eq!(round_tripped.path.nodes, proof.path.nodes);
+    assert!(round_tripped.verify(&key, &value, smt.get_root()));
+}
+
+#[test]
+fn test_compute_and_apply_mutations() {
+    let mut smt = Smt::<Impl>::new();
+    let mut reference = Smt::<Impl>::new();
+
+    let keys_and_values: Vec<(Key, Value)> = KeyIter::default()
+        .map(|key| (key, Value(key.0)))
+        .collect();
+    for (key, value) in keys_and_values.iter().copied() {
+        reference.insert(key, value);
+    }
+
+    let mutations = smt.compute_mutations(keys_and_values.iter().copied());
+    // Computing mutations must not touch the original tree.
+    assert_ne!(smt.get_root(), mutations.root());
+    assert_eq!(mutations.root(), reference.get_root());
+
+    smt.apply_mutations(mutations);
+    assert_eq!(smt.get_root(), reference.get_root());
+
+    for (key, value) in keys_and_values {
+        let (smt_value, proof) = smt.get(&key);
+        assert_eq!(smt_value, value);
+        assert!(proof.verify(&key, &value, smt.get_root()));
+    }
+}
+
+#[test]
+fn test_deferred_insert_and_remove() {
+    let mut smt = Smt::<Impl>::new();
+    let mut reference = Smt::<Impl>::new();
+
+    let keys_and_values: Vec<(Key, Value)> = KeyIter::default()
+        .map(|key| (key, Value(key.0)))
+        .collect();
+    for (key, value) in keys_and_values.iter().copied() {
+        reference.insert(key, value);
+        smt.insert_deferred(key, value);
+    }
+    smt.flush();
+    assert_eq!(smt.get_root(), reference.get_root());
+
+    let (key, _) = keys_and_values[1];
+    reference.remove(&key);
+    smt.remove_deferred(&key);
+
+    // get() implicitly flushes, so the read-back value and proof are both correct
+    // even though the root hasn't been recomputed since the deferred remove.
+    let (value, proof) = smt.get(&key);
+    assert_eq!(value, Value::EMPTY);
+    assert!(proof.verify(&key, &value, smt.get_root()));
+    assert_eq!(smt.get_root(), reference.get_root());
+}
+
 fn insert_and_check_proof(smt: &mut Smt<Impl>, key: Key, value: Value) -> Value {
     let old_value = smt.insert(key, value);
 